@@ -10,6 +10,7 @@ fn main() {
         height,
         num_bands,
         precision,
+        ..
     } = stream
         .decode(codec, jp2k::DecodeParams::default().with_reduce_factor(1))
         .unwrap();
@@ -0,0 +1,54 @@
+//! Color-space conversions that libopenjpeg itself does not perform: `opj_decode`
+//! hands back raw per-component samples tagged with an `OPJ_CLRSPC_*` color
+//! space, and leaves sYCC/CMYK conversion to the caller, the same way
+//! OpenJPEG's own `bin/jp2/convert.c` sample tool does before writing a file.
+//!
+//! Indexed/palette images are not handled here: OpenJPEG expands a JP2 palette
+//! into full component data itself while reading the header (`opj_jp2_apply_pclr`),
+//! so by the time `decode` sees the image the components are already plain
+//! samples in the image's declared color space.
+
+/// Inverse YCbCr -> RGB transform used for `OPJ_CLRSPC_SYCC` images, clamped to
+/// the component's precision.
+pub(crate) fn sycc_to_rgb(y: i32, cb: i32, cr: i32, prec: u32) -> (i32, i32, i32) {
+    let offset = 1 << (prec - 1);
+    let max = (1 << prec) - 1;
+    let cb = cb - offset;
+    let cr = cr - offset;
+
+    let r = y as f64 + 1.402 * cr as f64;
+    let g = y as f64 - 0.344 * cb as f64 - 0.714 * cr as f64;
+    let b = y as f64 + 1.772 * cb as f64;
+
+    (
+        (r.round() as i32).clamp(0, max),
+        (g.round() as i32).clamp(0, max),
+        (b.round() as i32).clamp(0, max),
+    )
+}
+
+/// CMYK -> RGB conversion used for `OPJ_CLRSPC_CMYK` images. `max` is the
+/// component's full-scale value, i.e. `(1 << prec) - 1`.
+pub(crate) fn cmyk_to_rgb(c: i32, m: i32, y: i32, k: i32, max: i32) -> (i32, i32, i32) {
+    // `c * (max - k)` overflows i32 for 16-bit precision (up to ~65535^2), so
+    // do the multiply in i64 before narrowing back down.
+    let (max, k) = (max as i64, k as i64);
+    let component = |v: i32| -> i32 {
+        let v = v as i64;
+        (max - (v * (max - k) / max + k).min(max)).max(0) as i32
+    };
+    (component(c), component(m), component(y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cmyk_to_rgb_16_bit_does_not_overflow() {
+        let max = (1 << 16) - 1;
+        assert_eq!(cmyk_to_rgb(max, max, max, max, max), (0, 0, 0));
+        assert_eq!(cmyk_to_rgb(0, 0, 0, 0, max), (max, max, max));
+        assert_eq!(cmyk_to_rgb(max, max, max, 0, max), (0, 0, 0));
+    }
+}
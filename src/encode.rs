@@ -0,0 +1,418 @@
+//! Encoding support: build JP2 / J2K codestreams from raw pixel buffers.
+//!
+//! This mirrors the decode side in `lib.rs`: an [`Encoder`] wraps
+//! `opj_create_compress`, [`EncodeParams`] mirrors `DecodeParams`, and
+//! [`Stream::to_writer`]/[`Stream::to_bytes`] install the write/seek/skip
+//! callbacks OpenJPEG needs to stream the encoded bytes out.
+
+use std::cell::RefCell;
+use std::io::{Seek, SeekFrom, Write};
+use std::os::raw::c_void;
+use std::rc::Rc;
+
+use openjpeg_sys as ffi;
+use ffi::{OPJ_FALSE, OPJ_TRUE};
+
+use crate::{err, Image, ImageBuffer, Stream};
+
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+pub use ffi::PROG_ORDER;
+
+struct InnerEncodeParams(ffi::opj_cparameters);
+
+impl Default for InnerEncodeParams {
+    fn default() -> Self {
+        let mut new = unsafe { std::mem::zeroed::<ffi::opj_cparameters>() };
+        unsafe {
+            ffi::opj_set_default_encoder_parameters(&mut new as *mut _);
+        }
+        InnerEncodeParams(new)
+    }
+}
+
+/// Parameters used to encode a JPEG2000 image
+#[derive(Debug, Clone, Default)]
+pub struct EncodeParams {
+    num_resolutions: Option<i32>,
+    quality_layers: Option<u32>,
+    compression_ratios: Vec<f32>,
+    psnr_targets: Vec<f32>,
+    tile_size: Option<(i32, i32)>,
+    codeblock_size: Option<(i32, i32)>,
+    irreversible: bool,
+    progression_order: Option<PROG_ORDER>,
+}
+
+impl EncodeParams {
+    /// Number of wavelet decomposition levels (resolutions)
+    pub fn with_num_resolutions(mut self, num_resolutions: i32) -> Self {
+        self.num_resolutions = Some(num_resolutions);
+        self
+    }
+
+    /// Number of quality layers to write. Only used while
+    /// [`Self::with_compression_ratios`]/[`Self::with_psnr_targets`] are left unset;
+    /// without it a single lossless layer is produced.
+    pub fn with_quality_layers(mut self, quality_layers: u32) -> Self {
+        self.quality_layers = Some(quality_layers);
+        self
+    }
+
+    /// Per-layer target compression ratios (`tcp_rates`), e.g. `&[20.0, 10.0, 1.0]`
+    /// for three layers from most to least compressed. A ratio of `1.0` is lossless.
+    pub fn with_compression_ratios(mut self, ratios: impl Into<Vec<f32>>) -> Self {
+        self.compression_ratios = ratios.into();
+        self
+    }
+
+    /// Per-layer target PSNR in dB (`tcp_distoratio`), used instead of explicit
+    /// compression ratios for quality-based rate control.
+    pub fn with_psnr_targets(mut self, targets: impl Into<Vec<f32>>) -> Self {
+        self.psnr_targets = targets.into();
+        self
+    }
+
+    /// Tile size in pixels. Without this OpenJPEG encodes a single tile covering
+    /// the whole image.
+    pub fn with_tile_size(mut self, width: i32, height: i32) -> Self {
+        self.tile_size = Some((width, height));
+        self
+    }
+
+    /// Codeblock size in pixels (each dimension must be a power of two, <= 64).
+    pub fn with_codeblock_size(mut self, width: i32, height: i32) -> Self {
+        self.codeblock_size = Some((width, height));
+        self
+    }
+
+    /// Use the irreversible 9-7 wavelet (lossy) when `true`, or the reversible
+    /// 5-3 wavelet (lossless) when `false`. Defaults to the reversible transform.
+    pub fn with_irreversible(mut self, irreversible: bool) -> Self {
+        self.irreversible = irreversible;
+        self
+    }
+
+    /// Order in which resolution/layer/component/precinct are interleaved in the
+    /// codestream.
+    pub fn with_progression_order(mut self, order: PROG_ORDER) -> Self {
+        self.progression_order = Some(order);
+        self
+    }
+
+    /// `tcp_rates`/`tcp_distoratio` in `opj_cparameters` are fixed `[f32; 100]`
+    /// arrays, so OpenJPEG can't represent more than this many quality layers.
+    const MAX_QUALITY_LAYERS: usize = 100;
+
+    fn apply(&self, p: &mut ffi::opj_cparameters) -> err::Result<()> {
+        if let Some(num_resolutions) = self.num_resolutions {
+            p.numresolution = num_resolutions;
+        }
+
+        if let Some((width, height)) = self.codeblock_size {
+            p.cblockw_init = width;
+            p.cblockh_init = height;
+        }
+
+        if let Some((width, height)) = self.tile_size {
+            p.tile_size_on = OPJ_TRUE as i32;
+            p.cp_tdx = width;
+            p.cp_tdy = height;
+        }
+
+        if !self.compression_ratios.is_empty() {
+            if self.compression_ratios.len() > Self::MAX_QUALITY_LAYERS {
+                return Err(err::Error::boxed(format!(
+                    "Too many compression ratios: {} exceeds the maximum of {} quality layers",
+                    self.compression_ratios.len(),
+                    Self::MAX_QUALITY_LAYERS
+                )));
+            }
+            p.tcp_numlayers = self.compression_ratios.len() as i32;
+            for (i, ratio) in self.compression_ratios.iter().enumerate() {
+                p.tcp_rates[i] = *ratio;
+            }
+            p.cp_disto_alloc = 1;
+        } else if !self.psnr_targets.is_empty() {
+            if self.psnr_targets.len() > Self::MAX_QUALITY_LAYERS {
+                return Err(err::Error::boxed(format!(
+                    "Too many PSNR targets: {} exceeds the maximum of {} quality layers",
+                    self.psnr_targets.len(),
+                    Self::MAX_QUALITY_LAYERS
+                )));
+            }
+            p.tcp_numlayers = self.psnr_targets.len() as i32;
+            for (i, psnr) in self.psnr_targets.iter().enumerate() {
+                p.tcp_distoratio[i] = *psnr;
+            }
+            p.cp_fixed_quality = 1;
+        } else {
+            let quality_layers = self.quality_layers.unwrap_or(1) as usize;
+            if quality_layers > Self::MAX_QUALITY_LAYERS {
+                return Err(err::Error::boxed(format!(
+                    "Too many quality layers: {} exceeds the maximum of {}",
+                    quality_layers,
+                    Self::MAX_QUALITY_LAYERS
+                )));
+            }
+            p.tcp_numlayers = quality_layers as i32;
+            p.cp_disto_alloc = 1;
+        }
+
+        p.irreversible = self.irreversible as i32;
+
+        if let Some(order) = self.progression_order {
+            p.prog_order = order;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::opj_destroy_codec(self.0.as_ptr());
+        }
+    }
+}
+
+/// Thin wrapper around the `opj_codec_t` struct, set up for compression
+pub struct Encoder(NonNull<ffi::opj_codec_t>);
+
+impl Encoder {
+    fn create(format: ffi::CODEC_FORMAT) -> Self {
+        // following unwrap is safe since unknown format is never used.
+        let ptr = unsafe { ffi::opj_create_compress(format) };
+        Encoder(NonNull::new(ptr).unwrap())
+    }
+
+    /// JPEG-2000 codestream : read/write
+    pub fn j2k() -> Self {
+        Self::create(ffi::CODEC_FORMAT::OPJ_CODEC_J2K)
+    }
+
+    /// JP2 file format : read/write
+    pub fn jp2() -> Self {
+        Self::create(ffi::CODEC_FORMAT::OPJ_CODEC_JP2)
+    }
+
+    /// Encode `image` into a JPEG2000 codestream/file, writing it to `stream`.
+    pub fn encode(self, stream: Stream, image: &ImageBuffer, params: EncodeParams) -> err::Result<()> {
+        let mut inner_params = InnerEncodeParams::default();
+        params.apply(&mut inner_params.0)?;
+
+        let opj_image = build_opj_image(image)?;
+
+        if unsafe { ffi::opj_setup_encoder(self.0.as_ptr(), &mut inner_params.0, opj_image.0) } != 1 {
+            return Err(err::Error::boxed("Setting up the encoder failed."));
+        }
+
+        if unsafe { ffi::opj_start_compress(self.0.as_ptr(), opj_image.0, stream.ptr) } != 1 {
+            return Err(err::Error::boxed("Starting compression failed."));
+        }
+
+        if unsafe { ffi::opj_encode(self.0.as_ptr(), stream.ptr) } != 1 {
+            return Err(err::Error::boxed("Encoding failed."));
+        }
+
+        if unsafe { ffi::opj_end_compress(self.0.as_ptr(), stream.ptr) } != 1 {
+            return Err(err::Error::boxed("Ending compression failed."));
+        }
+
+        Ok(())
+    }
+}
+
+/// Allocates an `opj_image_t` matching `image`'s dimensions/bands and copies the
+/// interleaved bytes back out into OpenJPEG's planar `i32` component arrays.
+fn build_opj_image(image: &ImageBuffer) -> err::Result<Image> {
+    let num_bands = image.num_bands as u32;
+
+    let color_space = match num_bands {
+        1 | 2 => ffi::COLOR_SPACE::OPJ_CLRSPC_GRAY,
+        3 | 4 => ffi::COLOR_SPACE::OPJ_CLRSPC_SRGB,
+        _ => {
+            return Err(err::Error::boxed(format!(
+                "Encoding not supported for {} bands",
+                num_bands
+            )))
+        }
+    };
+
+    if image.precision != 8 && image.precision != 16 {
+        return Err(err::Error::boxed(format!(
+            "Unsupported precision for encoding: {}",
+            image.precision
+        )));
+    }
+
+    let cmptparms: Vec<ffi::opj_image_cmptparm_t> = (0..num_bands)
+        .map(|_| ffi::opj_image_cmptparm_t {
+            dx: 1,
+            dy: 1,
+            w: image.width,
+            h: image.height,
+            x0: 0,
+            y0: 0,
+            prec: image.precision,
+            bpp: image.precision,
+            sgnd: 0,
+        })
+        .collect();
+
+    let ptr = unsafe { ffi::opj_image_create(num_bands, cmptparms.as_ptr() as *mut _, color_space) };
+    if ptr.is_null() {
+        return Err(err::Error::boxed("Failed to allocate image for encoding."));
+    }
+    let img = Image(ptr);
+
+    unsafe {
+        (*img.0).x0 = 0;
+        (*img.0).y0 = 0;
+        (*img.0).x1 = image.width;
+        (*img.0).y1 = image.height;
+    }
+
+    let pixels = (image.width * image.height) as usize;
+    let comps = unsafe { std::slice::from_raw_parts((*img.0).comps, num_bands as usize) };
+    let num_bands = num_bands as usize;
+
+    if image.precision == 8 {
+        for (band, comp) in comps.iter().enumerate() {
+            let data = unsafe { std::slice::from_raw_parts_mut(comp.data, pixels) };
+            for (i, px) in image.buffer.chunks_exact(num_bands).enumerate() {
+                data[i] = px[band] as i32;
+            }
+        }
+    } else {
+        let bytes_per_px = 2 * num_bands;
+        for (band, comp) in comps.iter().enumerate() {
+            let data = unsafe { std::slice::from_raw_parts_mut(comp.data, pixels) };
+            for (i, px) in image.buffer.chunks_exact(bytes_per_px).enumerate() {
+                data[i] = u16::from_ne_bytes([px[band * 2], px[band * 2 + 1]]) as i32;
+            }
+        }
+    }
+
+    Ok(img)
+}
+
+trait WriteSeek: Write + Seek {}
+impl<T: Write + Seek> WriteSeek for T {}
+
+/// In-memory sink backing [`Stream::to_bytes`]. Shared with the caller via `Rc`
+/// so the encoded bytes remain reachable after the `Stream` (and the write
+/// callbacks it owns) are dropped.
+#[derive(Clone)]
+struct SharedBuffer {
+    buf: Rc<RefCell<Vec<u8>>>,
+    pos: usize,
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let mut buf = self.buf.borrow_mut();
+        let end = self.pos + data.len();
+        if end > buf.len() {
+            buf.resize(end, 0);
+        }
+        buf[self.pos..end].copy_from_slice(data);
+        self.pos = end;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for SharedBuffer {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let len = self.buf.borrow().len() as i64;
+        let new_pos = match pos {
+            SeekFrom::Start(n) => n as i64,
+            SeekFrom::End(n) => len + n,
+            SeekFrom::Current(n) => self.pos as i64 + n,
+        };
+        if new_pos < 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "seek to a negative position",
+            ));
+        }
+        self.pos = new_pos as usize;
+        Ok(self.pos as u64)
+    }
+}
+
+unsafe extern "C" fn opj_stream_write_fn(
+    p_buffer: *mut c_void,
+    p_nb_bytes: usize,
+    p_user_data: *mut c_void,
+) -> usize {
+    let writer = p_user_data as *mut Box<dyn WriteSeek>;
+    let src = std::slice::from_raw_parts(p_buffer as *const u8, p_nb_bytes);
+    (*writer).write(src).unwrap_or(usize::MAX)
+}
+
+unsafe extern "C" fn opj_stream_skip_fn(
+    p_nb_bytes: ffi::OPJ_OFF_T,
+    p_user_data: *mut c_void,
+) -> ffi::OPJ_OFF_T {
+    let writer = p_user_data as *mut Box<dyn WriteSeek>;
+    // Same contract as the reader-side skip fn in lib.rs: return bytes skipped.
+    match (*writer).seek(SeekFrom::Current(p_nb_bytes)) {
+        Ok(_) => p_nb_bytes,
+        Err(_) => -1,
+    }
+}
+
+unsafe extern "C" fn opj_stream_seek_fn(p_nb_bytes: ffi::OPJ_OFF_T, p_user_data: *mut c_void) -> i32 {
+    let writer = p_user_data as *mut Box<dyn WriteSeek>;
+    match (*writer).seek(SeekFrom::Start(p_nb_bytes as u64)) {
+        Ok(_) => OPJ_TRUE as i32,
+        Err(_) => OPJ_FALSE as i32,
+    }
+}
+
+unsafe extern "C" fn opj_stream_free_write_user_data_fn(p_user_data: *mut c_void) {
+    drop(Box::from_raw(p_user_data as *mut Box<dyn WriteSeek>));
+}
+
+impl<'a> Stream<'a> {
+    /// Creates an output `Stream` that writes encoded bytes into `writer`.
+    pub fn to_writer<W: Write + Seek + 'a>(writer: W) -> err::Result<Self> {
+        let boxed: Box<dyn WriteSeek + 'a> = Box::new(writer);
+        let ptr = unsafe {
+            let jp2_stream = ffi::opj_stream_default_create(OPJ_FALSE as i32); // output stream
+            ffi::opj_stream_set_write_function(jp2_stream, Some(opj_stream_write_fn));
+            ffi::opj_stream_set_skip_function(jp2_stream, Some(opj_stream_skip_fn));
+            ffi::opj_stream_set_seek_function(jp2_stream, Some(opj_stream_seek_fn));
+            ffi::opj_stream_set_user_data(
+                jp2_stream,
+                Box::into_raw(Box::new(boxed)) as *mut c_void,
+                Some(opj_stream_free_write_user_data_fn),
+            );
+            jp2_stream
+        };
+
+        Ok(Stream {
+            ptr,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Creates an output `Stream` that writes into an in-memory buffer, returned
+    /// alongside the stream so the encoded bytes can be read back out once
+    /// encoding has finished.
+    pub fn to_bytes() -> err::Result<(Self, Rc<RefCell<Vec<u8>>>)> {
+        let buf = Rc::new(RefCell::new(Vec::new()));
+        let stream = Self::to_writer(SharedBuffer {
+            buf: buf.clone(),
+            pos: 0,
+        })?;
+        Ok((stream, buf))
+    }
+}
@@ -0,0 +1,115 @@
+//! `image` crate integration, enabled via the `image` feature. Implements
+//! `image::ImageDecoder` directly instead of leaving callers to hand-roll the
+//! `num_bands`/`precision` -> `ColorType` mapping shown in `examples/decode.rs`.
+
+use std::io::Cursor;
+
+use image::{ColorType, DynamicImage, ImageResult};
+
+use crate::{err, Codec, DecodeParams, ImageBuffer, Stream};
+
+/// Decodes a JPEG2000 image through the `image` crate's `ImageDecoder` trait.
+pub struct Jp2kDecoder {
+    image: ImageBuffer,
+}
+
+impl Jp2kDecoder {
+    /// Decodes the header and pixel data up front. `image::ImageDecoder` only
+    /// exposes an already-decoded reader, so there's nothing to gain from
+    /// deferring decode any further.
+    pub fn new(stream: Stream, codec: Codec, params: DecodeParams) -> err::Result<Self> {
+        let image = stream.decode(codec, params)?;
+        Ok(Jp2kDecoder { image })
+    }
+}
+
+impl<'a> image::ImageDecoder<'a> for Jp2kDecoder {
+    type Reader = Cursor<Vec<u8>>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.image.width, self.image.height)
+    }
+
+    fn color_type(&self) -> ColorType {
+        color_type_for(self.image.num_bands, self.image.precision)
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        Ok(Cursor::new(self.image.buffer))
+    }
+}
+
+fn color_type_for(num_bands: usize, precision: u32) -> ColorType {
+    match (num_bands, precision) {
+        (1, 8) => ColorType::L8,
+        (1, 16) => ColorType::L16,
+        (2, 8) => ColorType::La8,
+        (2, 16) => ColorType::La16,
+        (3, 8) => ColorType::Rgb8,
+        (3, 16) => ColorType::Rgb16,
+        (4, 8) => ColorType::Rgba8,
+        (4, 16) => ColorType::Rgba16,
+        _ => panic!(
+            "unsupported num_bands, precision: {}, {}",
+            num_bands, precision
+        ),
+    }
+}
+
+fn bytes_to_u16(buffer: &[u8]) -> Vec<u16> {
+    buffer
+        .chunks_exact(2)
+        .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+        .collect()
+}
+
+impl ImageBuffer {
+    /// Maps `(num_bands, precision)` to the matching `image::ColorType` and
+    /// wraps the buffer in a `DynamicImage`.
+    pub fn into_dynamic_image(self) -> DynamicImage {
+        let ImageBuffer {
+            buffer,
+            width,
+            height,
+            num_bands,
+            precision,
+            ..
+        } = self;
+
+        match (num_bands, precision) {
+            (1, 8) => DynamicImage::ImageLuma8(
+                image::GrayImage::from_raw(width, height, buffer).expect("buffer size mismatch"),
+            ),
+            (1, 16) => DynamicImage::ImageLuma16(
+                image::ImageBuffer::from_raw(width, height, bytes_to_u16(&buffer))
+                    .expect("buffer size mismatch"),
+            ),
+            (2, 8) => DynamicImage::ImageLumaA8(
+                image::GrayAlphaImage::from_raw(width, height, buffer)
+                    .expect("buffer size mismatch"),
+            ),
+            (2, 16) => DynamicImage::ImageLumaA16(
+                image::ImageBuffer::from_raw(width, height, bytes_to_u16(&buffer))
+                    .expect("buffer size mismatch"),
+            ),
+            (3, 8) => DynamicImage::ImageRgb8(
+                image::RgbImage::from_raw(width, height, buffer).expect("buffer size mismatch"),
+            ),
+            (3, 16) => DynamicImage::ImageRgb16(
+                image::ImageBuffer::from_raw(width, height, bytes_to_u16(&buffer))
+                    .expect("buffer size mismatch"),
+            ),
+            (4, 8) => DynamicImage::ImageRgba8(
+                image::RgbaImage::from_raw(width, height, buffer).expect("buffer size mismatch"),
+            ),
+            (4, 16) => DynamicImage::ImageRgba16(
+                image::ImageBuffer::from_raw(width, height, bytes_to_u16(&buffer))
+                    .expect("buffer size mismatch"),
+            ),
+            _ => panic!(
+                "unsupported num_bands, precision: {}, {}",
+                num_bands, precision
+            ),
+        }
+    }
+}
@@ -10,6 +10,8 @@ Forked from https://framagit.org/leoschwarz/jpeg2000-rust before its GPL-v3 reli
 * Improved OpenJPEG -> DynamicImage loading process
 * Get basic metadata from JPEG2000 headings
 * Docs (albeit minimal ones)
+* Encode JP2/J2K codestreams from raw buffers via the [`encode`] module
+* Optional `image` feature: `image_decoder::Jp2kDecoder` implements `image::ImageDecoder`, and `ImageBuffer::into_dynamic_image` converts directly
 
 This library brings its own libopenjpeg, which is statically linked. If you just need raw FFI bindings, see
 [openjpeg2-sys](https://crates.io/crates/openjpeg2-sys) or [openjpeg-sys](https://crates.io/crates/openjpeg-sys).
@@ -28,6 +30,7 @@ let jp2k::ImageBuffer {
     height,
     num_bands,
     precision,
+    ..
 } = stream.decode(codec, jp2k::DecodeParams::default()).unwrap();
 
 let color_type = match num_bands {
@@ -58,9 +61,13 @@ As soon as someone writes an efficient JPEG2000 decoder in pure Rust you should
 You can use the Rust code in the directories `src` and `openjp2-sys/src` under the terms of either the MIT license (`LICENSE-MIT` file) or the Apache license (`LICENSE-APACHE` file). Please note that this will link statically to OpenJPEG, which has its own license which you can find at `openjpeg-sys/libopenjpeg/LICENSE` (you might have to check out the git submodule first).
 */
 
+mod color;
+pub mod encode;
 pub mod err;
+#[cfg(feature = "image")]
+pub mod image_decoder;
 
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Seek, SeekFrom};
 use std::marker::PhantomData;
 use std::os::raw::c_void;
 use std::ptr::{self, NonNull};
@@ -98,6 +105,9 @@ pub struct DecodeParams {
     decoding_area: Option<DecodingArea>,
     quality_layers: Option<u32>,
     num_threads: Option<i32>,
+    skip_color_conversion: bool,
+    max_pixels: Option<u64>,
+    max_components: Option<u32>,
 }
 
 impl DecodeParams {
@@ -130,6 +140,29 @@ impl DecodeParams {
         self
     }
 
+    /// Whether `decode` converts sYCC/CMYK samples to RGB based on the image's
+    /// color space (on by default). Disable this to get the raw, unconverted
+    /// component data instead.
+    pub fn with_color_conversion(mut self, enabled: bool) -> Self {
+        self.skip_color_conversion = !enabled;
+        self
+    }
+
+    /// Reject images whose declared `width * height` exceeds `max_pixels`, checked
+    /// right after the header is read and before any pixel data is decoded. Guards
+    /// against decompression bombs where a tiny file claims an enormous canvas.
+    pub fn with_max_dimensions(mut self, max_pixels: u64) -> Self {
+        self.max_pixels = Some(max_pixels);
+        self
+    }
+
+    /// Reject images with more than `max_components` components, checked at the
+    /// same point as [`Self::with_max_dimensions`].
+    pub fn with_max_components(mut self, max_components: u32) -> Self {
+        self.max_components = Some(max_components);
+        self
+    }
+
     fn value_for_discard_level(u: u32, discard_level: u32) -> u32 {
         let div = 1 << discard_level;
         let quot = u / div;
@@ -142,6 +175,9 @@ impl DecodeParams {
     }
 }
 
+trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
 pub struct Stream<'a> {
     ptr: *mut ffi::opj_stream_t,
     phantom: PhantomData<&'a ()>,
@@ -191,9 +227,87 @@ impl<'a> Stream<'a> {
         Box::from_raw(p_user_data as *mut Cursor<&[u8]>);
     }
 
-    /// Decode a JPEG2000
-    pub fn decode(self, codec: Codec, params: DecodeParams) -> err::Result<ImageBuffer> {
-        let stream = self.ptr;
+    /// Decode from an arbitrary `Read + Seek` source (a file, a cursor, ...)
+    /// instead of a fully buffered `&[u8]`. OpenJPEG seeks within the source on
+    /// demand, so this allows true streaming decode of large images without
+    /// reading the whole codestream into memory up front.
+    pub fn from_reader<R: Read + Seek + 'a>(mut reader: R) -> err::Result<Self> {
+        // OpenJPEG needs the stream length up front (the same way `from_bytes`
+        // gets it for free from the slice length) to validate box/tile offsets,
+        // so measure it by seeking to the end and back to the start.
+        let length = reader
+            .seek(SeekFrom::End(0))
+            .map_err(err::Error::boxed)?;
+        reader
+            .seek(SeekFrom::Start(0))
+            .map_err(err::Error::boxed)?;
+
+        let boxed: Box<dyn ReadSeek + 'a> = Box::new(reader);
+        let ptr = unsafe {
+            let jp2_stream = ffi::opj_stream_default_create(OPJ_TRUE as i32); // input stream
+            ffi::opj_stream_set_read_function(jp2_stream, Some(Self::opj_stream_read_reader_fn));
+            ffi::opj_stream_set_skip_function(jp2_stream, Some(Self::opj_stream_skip_reader_fn));
+            ffi::opj_stream_set_seek_function(jp2_stream, Some(Self::opj_stream_seek_reader_fn));
+            ffi::opj_stream_set_user_data_length(jp2_stream, length);
+            ffi::opj_stream_set_user_data(
+                jp2_stream,
+                Box::into_raw(Box::new(boxed)) as *mut c_void,
+                Some(Self::opj_stream_free_reader_user_data_fn),
+            );
+            jp2_stream
+        };
+
+        Ok(Stream {
+            ptr,
+            phantom: PhantomData,
+        })
+    }
+
+    unsafe extern "C" fn opj_stream_read_reader_fn(
+        p_buffer: *mut c_void,
+        p_nb_bytes: usize,
+        p_user_data: *mut c_void,
+    ) -> usize {
+        let reader = p_user_data as *mut Box<dyn ReadSeek>;
+        let dst = std::slice::from_raw_parts_mut(p_buffer as *mut u8, p_nb_bytes);
+        match (*reader).read(dst) {
+            Ok(0) => usize::MAX, // OpenJPEG's convention for end-of-stream/error
+            Ok(n) => n,
+            Err(_) => usize::MAX,
+        }
+    }
+
+    unsafe extern "C" fn opj_stream_skip_reader_fn(
+        p_nb_bytes: ffi::OPJ_OFF_T,
+        p_user_data: *mut c_void,
+    ) -> ffi::OPJ_OFF_T {
+        let reader = p_user_data as *mut Box<dyn ReadSeek>;
+        // Returns bytes skipped, not the resulting offset (OpenJPEG's skip-fn contract).
+        match (*reader).seek(SeekFrom::Current(p_nb_bytes)) {
+            Ok(_) => p_nb_bytes,
+            Err(_) => -1,
+        }
+    }
+
+    unsafe extern "C" fn opj_stream_seek_reader_fn(
+        p_nb_bytes: ffi::OPJ_OFF_T,
+        p_user_data: *mut c_void,
+    ) -> i32 {
+        let reader = p_user_data as *mut Box<dyn ReadSeek>;
+        match (*reader).seek(SeekFrom::Start(p_nb_bytes as u64)) {
+            Ok(_) => OPJ_TRUE as i32,
+            Err(_) => ffi::OPJ_FALSE as i32,
+        }
+    }
+
+    unsafe extern "C" fn opj_stream_free_reader_user_data_fn(p_user_data: *mut c_void) {
+        drop(Box::from_raw(p_user_data as *mut Box<dyn ReadSeek>));
+    }
+
+    /// Runs the decoder setup shared by [`Stream::decode`] and [`Stream::decode_tiles`]:
+    /// configures the decoder, reads the header, applies the resource limits and
+    /// decoding area from `params`.
+    fn setup_decode(stream: *mut ffi::opj_stream_t, codec: &Codec, params: &DecodeParams) -> err::Result<Image> {
         let mut inner_params = InnerDecodeParams::default();
 
         if let Some(reduce_factor) = params.reduce_factor {
@@ -220,12 +334,40 @@ impl<'a> Stream<'a> {
             return Err(err::Error::boxed("Failed to read header."));
         }
 
+        if let Some(max_pixels) = params.max_pixels {
+            let pixels = img.width() as u64 * img.height() as u64;
+            if pixels > max_pixels {
+                return Err(err::Error::boxed(format!(
+                    "Declared image size {} pixels exceeds the configured limit of {} pixels",
+                    pixels, max_pixels
+                )));
+            }
+        }
+
+        if let Some(max_components) = params.max_components {
+            if img.num_components() > max_components {
+                return Err(err::Error::boxed(format!(
+                    "Declared component count {} exceeds the configured limit of {}",
+                    img.num_components(),
+                    max_components
+                )));
+            }
+        }
+
         if let Some(DecodingArea { x0, y0, x1, y1 }) = params.decoding_area {
             if unsafe { ffi::opj_set_decode_area(codec.0.as_ptr(), img.0, x0, y0, x1, y1) } != 1 {
                 return Err(err::Error::boxed("Setting up the decoding area failed."));
             }
         }
 
+        Ok(img)
+    }
+
+    /// Decode a JPEG2000
+    pub fn decode(self, codec: Codec, params: DecodeParams) -> err::Result<ImageBuffer> {
+        let stream = self.ptr;
+        let img = Self::setup_decode(stream, &codec, &params)?;
+
         if unsafe { ffi::opj_decode(codec.0.as_ptr(), stream, img.0) } != 1 {
             return Err(err::Error::boxed("Failed to read image."));
         }
@@ -241,95 +383,25 @@ impl<'a> Stream<'a> {
         let width = DecodeParams::value_for_discard_level(width, factor);
         let height = DecodeParams::value_for_discard_level(height, factor);
 
-        let num_bands;
-
-        let (buffer, precision) = unsafe {
-            match img.components() {
-                [comp_r] => {
-                    num_bands = 1;
-
-                    if comp_r.prec == 8 {
-                        let buffer =
-                            std::slice::from_raw_parts(comp_r.data, (width * height) as usize)
-                                .iter()
-                                .map(|x| *x as u8)
-                                .collect::<Vec<_>>();
-                        (buffer, 8)
-                    } else if comp_r.prec == 16 {
-                        let buffer =
-                            std::slice::from_raw_parts(comp_r.data, (width * height) as usize)
-                                .iter()
-                                .flat_map(|x| (*x as u16).to_ne_bytes())
-                                .collect::<Vec<_>>();
-                        (buffer, 16)
-                    } else {
-                        return Err(err::Error::boxed(format!(
-                            "Unsupported precision for grayscale: {}",
-                            comp_r.prec
-                        )));
-                    }
-                }
-
-                [comp_r, comp_g, comp_b] => {
-                    if comp_r.prec != 8 {
-                        return Err(err::Error::boxed(format!(
-                            "Unsupported precision for RGB: {}",
-                            comp_r.prec
-                        )));
-                    }
-                    let r = std::slice::from_raw_parts(comp_r.data, (width * height) as usize);
-                    let g = std::slice::from_raw_parts(comp_g.data, (width * height) as usize);
-                    let b = std::slice::from_raw_parts(comp_b.data, (width * height) as usize);
-
-                    num_bands = 3;
-
-                    let buffer = Vec::with_capacity((width * height * num_bands) as usize);
-
-                    (
-                        r.iter().zip(g.iter()).zip(b.iter()).fold(
-                            buffer,
-                            |mut acc, ((r, g), b)| {
-                                acc.extend_from_slice(&[*r as u8, *g as u8, *b as u8]);
-                                acc
-                            },
-                        ),
-                        8,
-                    )
-                }
-                [comp_r, comp_g, comp_b, comp_a] => {
-                    if comp_r.prec != 8 {
-                        return Err(err::Error::boxed(format!(
-                            "Unsupported precision for RGBA: {}",
-                            comp_r.prec
-                        )));
-                    }
-                    let r = std::slice::from_raw_parts(comp_r.data, (width * height) as usize);
-                    let g = std::slice::from_raw_parts(comp_g.data, (width * height) as usize);
-                    let b = std::slice::from_raw_parts(comp_b.data, (width * height) as usize);
-                    let a = std::slice::from_raw_parts(comp_a.data, (width * height) as usize);
-
-                    num_bands = 4;
-
-                    let buffer = Vec::with_capacity((width * height * num_bands) as usize);
-
-                    (
-                        r.iter().zip(g.iter()).zip(b.iter()).zip(a.iter()).fold(
-                            buffer,
-                            |mut acc, (((r, g), b), a)| {
-                                acc.extend_from_slice(&[*r as u8, *g as u8, *b as u8, *a as u8]);
-                                acc
-                            },
-                        ),
-                        8,
-                    )
-                }
-                _ => {
-                    return Err(err::Error::boxed(
-                        "Operation not supported for that number of components",
-                    ));
-                }
-            }
-        };
+        // Expand every component to the full width x height canvas, upsampling any
+        // chroma-subsampled (dx/dy > 1) components and recentering signed samples.
+        let bands: Vec<Vec<i32>> = img
+            .components()
+            .iter()
+            .map(|comp| expand_component(comp, width, height))
+            .collect();
+
+        let mut component_precisions: Vec<u32> = img.components().iter().map(|c| c.prec).collect();
+        let color_space = img.color_space();
+
+        let (buffer, num_bands, precision) = interleave_bands(
+            &bands,
+            &component_precisions,
+            color_space,
+            params.skip_color_conversion,
+        )?;
+        // CMYK -> RGB conversion drops a band; keep one precision per output band.
+        component_precisions.truncate(num_bands as usize);
 
         Ok(ImageBuffer {
             buffer,
@@ -337,10 +409,288 @@ impl<'a> Stream<'a> {
             height,
             num_bands: num_bands as usize,
             precision,
+            component_precisions,
+        })
+    }
+
+    /// Decode the image tile-by-tile instead of all at once, for out-of-core
+    /// processing or viewport rendering of gigapixel images. The returned
+    /// iterator drives `opj_read_tile_header`/`opj_decode_tile_data` one tile at
+    /// a time, so the caller can stop early without decoding the remaining tiles.
+    pub fn decode_tiles(self, codec: Codec, params: DecodeParams) -> err::Result<TileIter<'a>> {
+        let stream = self.ptr;
+        let img = Self::setup_decode(stream, &codec, &params)?;
+
+        // Unlike whole-image `decode`, OpenJPEG requires an explicit decode area
+        // before the `opj_read_tile_header`/`opj_decode_tile_data` loop; default
+        // to the full image (`0,0,0,0`) when the caller didn't request a
+        // sub-region via `DecodeParams::with_decoding_area` (already applied by
+        // `setup_decode` above in that case).
+        if params.decoding_area.is_none()
+            && unsafe { ffi::opj_set_decode_area(codec.0.as_ptr(), img.0, 0, 0, 0, 0) } != 1
+        {
+            return Err(err::Error::boxed("Setting up the decoding area failed."));
+        }
+
+        let component_precisions: Vec<u32> = img.components().iter().map(|c| c.prec).collect();
+        let component_sgnd: Vec<bool> = img.components().iter().map(|c| c.sgnd != 0).collect();
+        let component_dx: Vec<u32> = img.components().iter().map(|c| c.dx.max(1)).collect();
+        let component_dy: Vec<u32> = img.components().iter().map(|c| c.dy.max(1)).collect();
+        let color_space = img.color_space();
+
+        Ok(TileIter {
+            stream: self,
+            codec,
+            _img: img,
+            component_precisions,
+            component_sgnd,
+            component_dx,
+            component_dy,
+            color_space,
+            skip_color_conversion: params.skip_color_conversion,
+            done: false,
+            buffer: Vec::new(),
         })
     }
 }
 
+/// One decoded tile yielded by [`Stream::decode_tiles`].
+#[derive(Debug)]
+pub struct Tile {
+    pub index: u32,
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+    pub image: ImageBuffer,
+}
+
+/// Iterator over the tiles of a JPEG2000 image, returned by [`Stream::decode_tiles`].
+pub struct TileIter<'a> {
+    stream: Stream<'a>,
+    codec: Codec,
+    // kept alive for the duration of decoding: per-component metadata was
+    // copied out of it by `decode_tiles`, and OpenJPEG keeps a pointer to the
+    // underlying `opj_image_t` for the lifetime of the codec.
+    _img: Image,
+    component_precisions: Vec<u32>,
+    component_sgnd: Vec<bool>,
+    // chroma-subsampling factors per component, needed to map the packed tile
+    // buffer's per-component sample count back to pixels (see `unpack_tile_bands`).
+    component_dx: Vec<u32>,
+    component_dy: Vec<u32>,
+    color_space: ffi::COLOR_SPACE,
+    skip_color_conversion: bool,
+    done: bool,
+    buffer: Vec<u8>,
+}
+
+impl<'a> Iterator for TileIter<'a> {
+    type Item = err::Result<Tile>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut tile_index = 0u32;
+        let mut data_size = 0u32;
+        let mut x0 = 0i32;
+        let mut y0 = 0i32;
+        let mut x1 = 0i32;
+        let mut y1 = 0i32;
+        let mut nb_comps = 0u32;
+        let mut should_continue = 0i32;
+
+        let ok = unsafe {
+            ffi::opj_read_tile_header(
+                self.codec.0.as_ptr(),
+                self.stream.ptr,
+                &mut tile_index,
+                &mut data_size,
+                &mut x0,
+                &mut y0,
+                &mut x1,
+                &mut y1,
+                &mut nb_comps,
+                &mut should_continue,
+            )
+        };
+
+        if ok != 1 {
+            self.done = true;
+            return Some(Err(err::Error::boxed("Failed to read tile header.")));
+        }
+
+        if should_continue != 1 {
+            self.done = true;
+            return None;
+        }
+
+        if self.buffer.len() < data_size as usize {
+            self.buffer.resize(data_size as usize, 0);
+        }
+
+        let ok = unsafe {
+            ffi::opj_decode_tile_data(
+                self.codec.0.as_ptr(),
+                tile_index,
+                self.buffer.as_mut_ptr(),
+                data_size,
+                self.stream.ptr,
+            )
+        };
+
+        if ok != 1 {
+            self.done = true;
+            return Some(Err(err::Error::boxed("Failed to decode tile data.")));
+        }
+
+        let width = (x1 - x0) as u32;
+        let height = (y1 - y0) as u32;
+
+        let component_precisions = &self.component_precisions[..nb_comps as usize];
+        let component_sgnd = &self.component_sgnd[..nb_comps as usize];
+        let component_dx = &self.component_dx[..nb_comps as usize];
+        let component_dy = &self.component_dy[..nb_comps as usize];
+
+        let bands = match unpack_tile_bands(
+            &self.buffer[..data_size as usize],
+            x0 as u32,
+            y0 as u32,
+            x1 as u32,
+            y1 as u32,
+            component_precisions,
+            component_sgnd,
+            component_dx,
+            component_dy,
+        ) {
+            Ok(bands) => bands,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+
+        match interleave_bands(
+            &bands,
+            component_precisions,
+            self.color_space,
+            self.skip_color_conversion,
+        ) {
+            Ok((buffer, num_bands, precision)) => Some(Ok(Tile {
+                index: tile_index,
+                x0: x0 as u32,
+                y0: y0 as u32,
+                x1: x1 as u32,
+                y1: y1 as u32,
+                image: ImageBuffer {
+                    buffer,
+                    width,
+                    height,
+                    num_bands: num_bands as usize,
+                    precision,
+                    component_precisions: component_precisions[..num_bands as usize].to_vec(),
+                },
+            })),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Ceiling division, matching OpenJPEG's own `int_ceildiv`: used to derive a
+/// chroma-subsampled component's sample count from the tile's reference-grid
+/// bounds, the same way the library derives `opj_image_comp_t::w`/`h` from the
+/// whole image's bounds.
+fn ceil_div(a: u32, b: u32) -> u32 {
+    (a + b - 1) / b
+}
+
+/// Unpacks the packed per-component byte buffer `opj_decode_tile_data` fills
+/// (all of component 0's samples at its own subsampled resolution, then all
+/// of component 1's, ...) into the same per-component, full tile-resolution
+/// `Vec<i32>` bands `expand_component` produces for [`Stream::decode`]:
+/// upsampling any chroma-subsampled (`dx`/`dy` > 1) component and recentering
+/// signed samples the same way, so [`interleave_bands`] can't tell the two
+/// callers apart.
+fn unpack_tile_bands(
+    data: &[u8],
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    component_precisions: &[u32],
+    component_sgnd: &[bool],
+    component_dx: &[u32],
+    component_dy: &[u32],
+) -> err::Result<Vec<Vec<i32>>> {
+    let width = x1 - x0;
+    let height = y1 - y0;
+    let mut offset = 0usize;
+    let mut bands = Vec::with_capacity(component_precisions.len());
+
+    for (((&prec, &sgnd), &dx), &dy) in component_precisions
+        .iter()
+        .zip(component_sgnd.iter())
+        .zip(component_dx.iter())
+        .zip(component_dy.iter())
+    {
+        // The samples OpenJPEG packs for this component cover its own
+        // subsampled resolution within the tile, not the tile's full width x
+        // height (see `expand_component`, which does the same derivation for
+        // the whole image).
+        let comp_w = ceil_div(x1, dx) - ceil_div(x0, dx);
+        let comp_h = ceil_div(y1, dy) - ceil_div(y0, dy);
+        let comp_pixels = (comp_w * comp_h) as usize;
+        let recenter = if sgnd { 1i32 << (prec - 1) } else { 0 };
+
+        let samples: Vec<i32> = if prec <= 8 {
+            let samples = &data[offset..offset + comp_pixels];
+            offset += comp_pixels;
+            // OpenJPEG packs signed samples as `i8`/`i16`, unsigned as `u8`/`u16`.
+            samples
+                .iter()
+                .map(|&b| (if sgnd { b as i8 as i32 } else { b as i32 }) + recenter)
+                .collect()
+        } else if prec <= 16 {
+            let samples = &data[offset..offset + comp_pixels * 2];
+            offset += comp_pixels * 2;
+            samples
+                .chunks_exact(2)
+                .map(|b| {
+                    let v = u16::from_ne_bytes([b[0], b[1]]);
+                    (if sgnd { v as i16 as i32 } else { v as i32 }) + recenter
+                })
+                .collect()
+        } else {
+            return Err(err::Error::boxed(format!(
+                "Unsupported precision for tile component: {}",
+                prec
+            )));
+        };
+
+        // Upsample to the tile's full width x height via the same
+        // nearest-neighbor mapping `expand_component` uses.
+        let comp_w = comp_w.max(1);
+        let comp_h = comp_h.max(1);
+        let mut band = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            let sy = (y / dy).min(comp_h - 1);
+            for x in 0..width {
+                let sx = (x / dx).min(comp_w - 1);
+                band.push(samples[(sy * comp_w + sx) as usize]);
+            }
+        }
+
+        bands.push(band);
+    }
+
+    Ok(bands)
+}
+
 impl Drop for Codec {
     fn drop(&mut self) {
         unsafe {
@@ -457,13 +807,160 @@ impl Image {
     }
 }
 
+/// Packs expanded per-component bands into the buffer layout [`ImageBuffer`]
+/// exposes, shared by [`Stream::decode`] and the tile-by-tile path in
+/// [`Stream::decode_tiles`] so both report pixels the same way for the same
+/// source image.
+fn interleave_bands(
+    bands: &[Vec<i32>],
+    component_precisions: &[u32],
+    color_space: ffi::COLOR_SPACE,
+    skip_color_conversion: bool,
+) -> err::Result<(Vec<u8>, u32, u32)> {
+    let uniform_precision = component_precisions.windows(2).all(|w| w[0] == w[1]);
+
+    if uniform_precision
+        && matches!(bands.len(), 1 | 2 | 3 | 4)
+        && matches!(component_precisions[0], 8 | 16)
+    {
+        // Common case: 1/2/3/4-band images sharing an 8- or 16-bit precision
+        // interleave into L/LA/RGB/RGBA (the layouts `image_decoder.rs` maps to
+        // an `image::ColorType`; `examples/decode.rs` maps the 8-bit ones and
+        // 16-bit L),
+        // applying sYCC/CMYK conversion where applicable.
+        let prec = component_precisions[0];
+        let push_sample = |buffer: &mut Vec<u8>, v: i32| {
+            if prec == 8 {
+                buffer.push(v as u8);
+            } else {
+                buffer.extend_from_slice(&(v as u16).to_ne_bytes());
+            }
+        };
+
+        let (buffer, num_bands) = match bands {
+            [r] => {
+                let mut buffer = Vec::new();
+                for r in r.iter() {
+                    push_sample(&mut buffer, *r);
+                }
+                (buffer, 1)
+            }
+            [l, a] => {
+                let mut buffer = Vec::new();
+                for (l, a) in l.iter().zip(a.iter()) {
+                    push_sample(&mut buffer, *l);
+                    push_sample(&mut buffer, *a);
+                }
+                (buffer, 2)
+            }
+            [r, g, b] => {
+                let apply_sycc = !skip_color_conversion
+                    && color_space == ffi::COLOR_SPACE::OPJ_CLRSPC_SYCC;
+
+                let mut buffer = Vec::new();
+                for ((r, g), b) in r.iter().zip(g.iter()).zip(b.iter()) {
+                    let (r, g, b) = if apply_sycc {
+                        color::sycc_to_rgb(*r, *g, *b, prec)
+                    } else {
+                        (*r, *g, *b)
+                    };
+                    push_sample(&mut buffer, r);
+                    push_sample(&mut buffer, g);
+                    push_sample(&mut buffer, b);
+                }
+                (buffer, 3)
+            }
+            [r, g, b, a] => {
+                if !skip_color_conversion && color_space == ffi::COLOR_SPACE::OPJ_CLRSPC_CMYK {
+                    // CMYK has no alpha channel; the fourth component is black (K).
+                    let max = (1i32 << prec) - 1;
+                    let mut buffer = Vec::new();
+                    for (((c, m), y), k) in r.iter().zip(g.iter()).zip(b.iter()).zip(a.iter()) {
+                        let (r, g, b) = color::cmyk_to_rgb(*c, *m, *y, *k, max);
+                        push_sample(&mut buffer, r);
+                        push_sample(&mut buffer, g);
+                        push_sample(&mut buffer, b);
+                    }
+                    (buffer, 3)
+                } else {
+                    let mut buffer = Vec::new();
+                    for (((r, g), b), a) in r.iter().zip(g.iter()).zip(b.iter()).zip(a.iter()) {
+                        push_sample(&mut buffer, *r);
+                        push_sample(&mut buffer, *g);
+                        push_sample(&mut buffer, *b);
+                        push_sample(&mut buffer, *a);
+                    }
+                    (buffer, 4)
+                }
+            }
+            _ => unreachable!("matches!(bands.len(), 1 | 2 | 3 | 4) guards this"),
+        };
+
+        Ok((buffer, num_bands, prec))
+    } else {
+        // General case: any number of components (e.g. multispectral data) or
+        // components that don't share a precision. Each component's samples
+        // are narrowed independently and stored planar (all of band 0, then
+        // all of band 1, ...) rather than interleaved, since there is no
+        // single per-pixel stride that would fit every band.
+        let mut buffer = Vec::new();
+        for (band, prec) in bands.iter().zip(component_precisions.iter()) {
+            if *prec <= 8 {
+                buffer.extend(band.iter().map(|x| *x as u8));
+            } else if *prec <= 16 {
+                buffer.extend(band.iter().flat_map(|x| (*x as u16).to_ne_bytes()));
+            } else {
+                return Err(err::Error::boxed(format!(
+                    "Unsupported precision for component: {}",
+                    prec
+                )));
+            }
+        }
+
+        let max_precision = component_precisions.iter().copied().max().unwrap_or(0);
+        Ok((buffer, bands.len() as u32, max_precision))
+    }
+}
+
+/// Expands a single component's raw samples to the full `width x height`
+/// canvas: nearest-neighbor upsampling for any `dx`/`dy` chroma subsampling,
+/// and shifting signed samples (`comp.sgnd`) up by `1 << (prec - 1)` into the
+/// unsigned range.
+fn expand_component(comp: &ffi::opj_image_comp_t, width: u32, height: u32) -> Vec<i32> {
+    let comp_w = comp.w.max(1);
+    let comp_h = comp.h.max(1);
+    let dx = comp.dx.max(1);
+    let dy = comp.dy.max(1);
+
+    let data = unsafe { std::slice::from_raw_parts(comp.data, (comp_w * comp_h) as usize) };
+    let offset = if comp.sgnd != 0 { 1i32 << (comp.prec - 1) } else { 0 };
+
+    let mut out = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        let sy = (y / dy).min(comp_h - 1);
+        for x in 0..width {
+            let sx = (x / dx).min(comp_w - 1);
+            out.push(data[(sy * comp_w + sx) as usize] + offset);
+        }
+    }
+    out
+}
+
 pub struct Component(*mut ffi::opj_image_comp_t);
 
 #[derive(Debug)]
 pub struct ImageBuffer {
+    /// Pixel data. Interleaved (e.g. RGBRGB...) when `num_bands` is 1 to 4
+    /// and every component shares a `precision` of 8 or 16; otherwise planar, with each
+    /// component's full `width * height` samples stored contiguously in
+    /// component order (see `component_precisions`).
     pub buffer: Vec<u8>,
     pub width: u32,
     pub height: u32,
     pub num_bands: usize,
     pub precision: u32,
+    /// Per-component precision, one entry per band. Only differs from a
+    /// uniform `vec![precision; num_bands]` for planar buffers whose
+    /// components don't all share a bit depth.
+    pub component_precisions: Vec<u32>,
 }